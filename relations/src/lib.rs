@@ -1,6 +1,7 @@
 //! Core interface for working with various relations that are useful in
-//! zkSNARKs. At the moment, we only implement APIs for working with Rank-1
-//! Constraint Systems (R1CS).
+//! zkSNARKs. At the moment, we implement APIs for working with Rank-1
+//! Constraint Systems (R1CS), and for converting them to Squared Rank-1
+//! Constraint Systems (SR1CS).
 
 #![cfg_attr(not(feature = "std"), no_std)]
 #![warn(
@@ -16,3 +17,4 @@
 extern crate ark_std;
 
 pub mod r1cs;
+pub mod sr1cs;
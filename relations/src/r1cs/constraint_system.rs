@@ -6,7 +6,7 @@ use ark_std::{
     any::{Any, TypeId},
     boxed::Box,
     cell::{Ref, RefCell, RefMut},
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     format,
     rc::Rc,
     string::String,
@@ -62,6 +62,16 @@ pub struct ConstraintSystem<F: Field> {
     pub cache_map: Rc<RefCell<BTreeMap<TypeId, Box<dyn Any>>>>,
 
     lc_map: BTreeMap<LcIndex, LinearCombination<F>>,
+    /// The linear combination `1 * Variable::One`, which is shared by all
+    /// constraints that have it as their A or B side.
+    one_lc: Option<LcIndex>,
+
+    committed_witnesses: BTreeMap<usize, Vec<Variable>>,
+
+    constraint_messages: BTreeMap<usize, &'static str>,
+
+    constraint_budgets: Vec<ConstraintBudget>,
+    next_budget_id: usize,
 
     #[cfg(feature = "std")]
     constraint_traces: Vec<Option<ConstraintTrace>>,
@@ -70,7 +80,7 @@ pub struct ConstraintSystem<F: Field> {
     b_constraints: Vec<LcIndex>,
     c_constraints: Vec<LcIndex>,
 
-    lc_assignment_cache: Rc<RefCell<BTreeMap<LcIndex, F>>>,
+    lc_assignment_cache: RefCell<LcAssignmentCache<F>>,
 }
 
 impl<F: Field> Default for ConstraintSystem<F> {
@@ -79,6 +89,80 @@ impl<F: Field> Default for ConstraintSystem<F> {
     }
 }
 
+/// A limit on the number of constraints that may be enforced while a
+/// `Namespace` is alive.
+#[derive(Copy, Clone, Debug)]
+struct ConstraintBudget {
+    id: usize,
+    ns: &'static str,
+    start: usize,
+    budget: usize,
+}
+
+/// The values of symbolic linear combinations (those created by `new_lc`).
+///
+/// Only symbolic LCs are ever evaluated through `assigned_value`, so values
+/// are only stored for each of them, rather than for every LC of every
+/// constraint. An entry is only valid if it was written in the current
+/// generation, so that the whole cache can be invalidated in constant time.
+#[derive(Clone, Debug)]
+struct LcAssignmentCache<F: Field> {
+    generation: u64,
+    /// The value of the LC at index `i` is cached in `values[slots[i]]`, or
+    /// not at all if `slots[i] == NO_SLOT`.
+    slots: Vec<u32>,
+    values: Vec<(u64, F)>,
+}
+
+const NO_SLOT: u32 = u32::MAX;
+
+impl<F: Field> LcAssignmentCache<F> {
+    fn new() -> Self {
+        Self {
+            generation: 1,
+            slots: Vec::new(),
+            values: Vec::new(),
+        }
+    }
+
+    /// Records that `index` is a symbolic LC, giving it a slot.
+    fn register(&mut self, index: LcIndex) {
+        let slot = u32::try_from(self.values.len())
+            .ok()
+            .filter(|&slot| slot != NO_SLOT)
+            .expect("too many symbolic linear combinations");
+        if index.0 >= self.slots.len() {
+            self.slots.resize(index.0 + 1, NO_SLOT);
+        }
+        self.slots[index.0] = slot;
+        self.values.push((0, F::zero()));
+    }
+
+    fn slot(&self, index: LcIndex) -> Option<usize> {
+        match self.slots.get(index.0) {
+            Some(&slot) if slot != NO_SLOT => Some(slot as usize),
+            _ => None,
+        }
+    }
+
+    fn get(&self, index: LcIndex) -> Option<F> {
+        match self.values.get(self.slot(index)?) {
+            Some(&(generation, value)) if generation == self.generation => Some(value),
+            _ => None,
+        }
+    }
+
+    fn insert(&mut self, index: LcIndex, value: F) {
+        if let Some(slot) = self.slot(index) {
+            self.values[slot] = (self.generation, value);
+        }
+    }
+
+    fn invalidate(&mut self) {
+        self.generation += 1;
+    }
+}
+
 /// Defines the mode of operation of a `ConstraintSystem`.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
 pub enum SynthesisMode {
@@ -142,7 +226,12 @@ impl<F: Field> ConstraintSystem<F> {
             constraint_traces: Vec::new(),
 
             lc_map: BTreeMap::new(),
-            lc_assignment_cache: Rc::new(RefCell::new(BTreeMap::new())),
+            one_lc: None,
+            committed_witnesses: BTreeMap::new(),
+            constraint_messages: BTreeMap::new(),
+            constraint_budgets: Vec::new(),
+            next_budget_id: 0,
+            lc_assignment_cache: RefCell::new(LcAssignmentCache::new()),
 
             mode: SynthesisMode::Prove {
                 construct_matrices: true,
@@ -157,6 +246,22 @@ impl<F: Field> ConstraintSystem<F> {
         ConstraintSystemRef::new(Self::new())
     }
 
+    /// Returns an independent copy of `self`, including its variables,
+    /// assignments, and constraints, so that synthesis can be resumed
+    /// several times from the same partial state.
+    ///
+    /// Unlike `clone`, the copy does not share `cache_map` with `self`.
+    /// The gadget cache starts out empty, because
+    /// cached gadgets may hold references to `self`, and the constraint
+    /// budgets of any live `Namespace`s are not carried over.
+    pub fn snapshot(&self) -> Self {
+        Self {
+            cache_map: Rc::new(RefCell::new(BTreeMap::new())),
+            constraint_budgets: Vec::new(),
+            ..self.clone()
+        }
+    }
+
     /// Set `self.mode` to `mode`.
     pub fn set_mode(&mut self, mode: SynthesisMode) {
         self.mode = mode;
@@ -237,30 +342,106 @@ impl<F: Field> ConstraintSystem<F> {
         Ok(Variable::Witness(index))
     }
 
+    /// Obtain a variable representing a new private witness input that belongs
+    /// to the externally committed block `block_id`.
+    ///
+    /// Committed witnesses are ordinary witness variables, but they are also
+    /// recorded in `self.committed_blocks()`, so that commit-and-prove
+    /// backends can link them to an external commitment.
+    #[inline]
+    pub fn new_committed_witness_variable<Func>(
+        &mut self,
+        block_id: usize,
+        f: Func,
+    ) -> crate::r1cs::Result<Variable>
+    where
+        Func: FnOnce() -> crate::r1cs::Result<F>,
+    {
+        let var = self.new_witness_variable(f)?;
+        self.committed_witnesses
+            .entry(block_id)
+            .or_default()
+            .push(var);
+        Ok(var)
+    }
+
+    /// The witness variables of each externally committed block, indexed by
+    /// block id, in the order in which they were allocated.
+    pub fn committed_blocks(&self) -> &BTreeMap<usize, Vec<Variable>> {
+        &self.committed_witnesses
+    }
+
     /// Obtain a variable representing a linear combination.
     #[inline]
     pub fn new_lc(&mut self, lc: LinearCombination<F>) -> crate::r1cs::Result<Variable> {
-        let index = LcIndex(self.num_linear_combinations);
-        let var = Variable::SymbolicLc(index);
+        let index = self.alloc_lc(lc);
+        self.lc_assignment_cache.borrow_mut().register(index);
+        Ok(Variable::SymbolicLc(index))
+    }
 
+    /// Adds `lc` to `self.lc_map`. Unlike `new_lc`, the LC is not symbolic,
+    /// and its value is never cached.
+    #[inline]
+    fn alloc_lc(&mut self, lc: LinearCombination<F>) -> LcIndex {
+        let index = LcIndex(self.num_linear_combinations);
         self.lc_map.insert(index, lc);
-
         self.num_linear_combinations += 1;
-        Ok(var)
+        index
     }
 
     /// Enforce a R1CS constraint with the name `name`.
+    ///
+    /// Returns `SynthesisError::BudgetExceeded` if the constraint would exceed
+    /// the budget of an enclosing `Namespace`.
     #[inline]
     pub fn enforce_constraint(
         &mut self,
         a: LinearCombination<F>,
         b: LinearCombination<F>,
         c: LinearCombination<F>,
+    ) -> crate::r1cs::Result<()> {
+        for budget in &self.constraint_budgets {
+            let used = self.num_constraints + 1 - budget.start;
+            if used > budget.budget {
+                return Err(SynthesisError::BudgetExceeded {
+                    ns: budget.ns,
+                    used,
+                    budget: budget.budget,
+                });
+            }
+        }
+        self.push_constraint(a, b, c)
+    }
+
+    /// Enforce the linear constraint `lc = 0`, i.e., the R1CS constraint
+    /// `lc * 1 = 0`.
+    ///
+    /// Like every constraint whose A or B side is the constant one, the
+    /// constraint reuses a single linear combination for that side, and is
+    /// listed by `ConstraintMatrices::linear_constraints`.
+    #[inline]
+    pub fn enforce_linear_constraint(
+        &mut self,
+        lc: LinearCombination<F>,
+    ) -> crate::r1cs::Result<()> {
+        self.enforce_constraint(
+            lc,
+            LinearCombination::from(Self::one()),
+            LinearCombination::zero(),
+        )
+    }
+
+    #[inline]
+    fn push_constraint(
+        &mut self,
+        a: LinearCombination<F>,
+        b: LinearCombination<F>,
+        c: LinearCombination<F>,
     ) -> crate::r1cs::Result<()> {
         if self.should_construct_matrices() {
-            let a_index = self.new_lc(a)?.get_lc_index().unwrap();
-            let b_index = self.new_lc(b)?.get_lc_index().unwrap();
-            let c_index = self.new_lc(c)?.get_lc_index().unwrap();
+            let a_index = self.constraint_lc(a)?;
+            let b_index = self.constraint_lc(b)?;
+            let c_index = self.alloc_lc(c);
             self.a_constraints.push(a_index);
             self.b_constraints.push(b_index);
             self.c_constraints.push(c_index);
@@ -274,10 +455,63 @@ impl<F: Field> ConstraintSystem<F> {
         Ok(())
     }
 
+    /// Returns the index of `lc` in `self.lc_map`, reusing `self.one_lc` if
+    /// `lc` is the constant one.
+    fn constraint_lc(&mut self, lc: LinearCombination<F>) -> crate::r1cs::Result<LcIndex> {
+        if lc.0 != [(F::one(), Variable::One)] {
+            return Ok(self.alloc_lc(lc));
+        }
+        match self.one_lc {
+            Some(index) => Ok(index),
+            None => {
+                let index = self.alloc_lc(lc);
+                self.one_lc = Some(index);
+                Ok(index)
+            },
+        }
+    }
+
+    /// Enforce a R1CS constraint, labelled with the human-readable `message`.
+    /// If this constraint is the first unsatisfied one, `which_is_unsatisfied`
+    /// reports `message` alongside the constraint trace.
+    #[inline]
+    pub fn enforce_constraint_with_message(
+        &mut self,
+        a: LinearCombination<F>,
+        b: LinearCombination<F>,
+        c: LinearCombination<F>,
+        message: &'static str,
+    ) -> crate::r1cs::Result<()> {
+        self.enforce_constraint(a, b, c)?;
+        self.constraint_messages
+            .insert(self.num_constraints - 1, message);
+        Ok(())
+    }
+
+    /// Starts limiting the number of constraints to `budget` until
+    /// `remove_constraint_budget` is called with the returned id.
+    fn add_constraint_budget(&mut self, ns: &'static str, budget: usize) -> usize {
+        let id = self.next_budget_id;
+        self.next_budget_id += 1;
+        self.constraint_budgets.push(ConstraintBudget {
+            id,
+            ns,
+            start: self.num_constraints,
+            budget,
+        });
+        id
+    }
+
+    fn remove_constraint_budget(&mut self, id: usize) {
+        self.constraint_budgets.retain(|budget| budget.id != id);
+    }
+
     /// Count the number of times each LC is used within other LCs in the
     /// constraint system
     fn lc_num_times_used(&self, count_sinks: bool) -> Vec<usize> {
-        let mut num_times_used = vec![0; self.lc_map.len()];
+        // `lc_map` may have gaps once LCs have been removed (e.g., by
+        // `optimize`), so size the vector by the number of LCs ever created.
+        let mut num_times_used = vec![0; self.num_linear_combinations];
 
         // Iterate over every lc in constraint system
         for (index, lc) in self.lc_map.iter() {
@@ -501,7 +735,7 @@ impl<F: Field> ConstraintSystem<F> {
                 .zip(new_witness_indices.iter())
         {
             // Add a new constraint
-            self.enforce_constraint(
+            self.push_constraint(
                 new_witness_linear_combination.clone(),
                 LinearCombination::from(Self::one()),
                 LinearCombination::from(Variable::Witness(*new_witness_variable)),
@@ -520,6 +754,260 @@ impl<F: Field> ConstraintSystem<F> {
         };
     }
 
+    /// Finalize the constraint system for `goal` (as in `finalize`), and then,
+    /// unless `goal == OptimizationGoal::None`, run the following passes:
+    ///
+    /// * constraints whose linear combinations only contain constants are
+    ///   removed, provided that they are satisfied;
+    /// * of two constraints of the form `a * b = w_1` and `a * b = w_2`, where
+    ///   `w_1` and `w_2` are witness variables that do not occur in `a` or
+    ///   `b`, the second is removed and `w_2` is replaced by `w_1` everywhere;
+    /// * witness variables that are not used in any constraint are removed,
+    ///   and the remaining witness variables are renumbered.
+    ///
+    /// Committed witness variables are never merged or removed, but they may
+    /// be renumbered, as reflected in `committed_blocks`.
+    ///
+    /// These passes only look at the structure of the constraints, so the
+    /// same matrices and witness layout are obtained in setup mode and in
+    /// proving mode with `construct_matrices == true`.
+    ///
+    /// This must be called after constraint generation has completed.
+    /// Because witness variables may be renumbered, `Variable`s and symbolic
+    /// LCs obtained before the call must not be used afterwards. Calling
+    /// `finalize` or `optimize` again afterwards is allowed.
+    ///
+    /// # Panics
+    /// This method panics if `self` does not construct matrices, since the
+    /// witness assignment could then not be renumbered consistently with
+    /// setup mode.
+    pub fn optimize(&mut self, goal: OptimizationGoal) {
+        assert!(
+            self.should_construct_matrices(),
+            "`optimize` requires a constraint system that constructs matrices"
+        );
+        match goal {
+            OptimizationGoal::None | OptimizationGoal::Constraints => self.inline_all_lcs(),
+            OptimizationGoal::Weight => self.outline_lcs(),
+        };
+        if goal == OptimizationGoal::None {
+            return;
+        }
+        self.fold_constant_constraints();
+        self.merge_equal_witnesses();
+        self.remove_unused_witnesses();
+        self.lc_assignment_cache.borrow_mut().invalidate();
+    }
+
+    /// Returns the value of the LC at `index` if it only contains constants.
+    fn constant_value(&self, index: &LcIndex) -> Option<F> {
+        let mut acc = F::zero();
+        for (coeff, var) in self.lc_map[index].iter() {
+            match var {
+                Variable::Zero => {},
+                Variable::One => acc += coeff,
+                _ => return None,
+            }
+        }
+        Some(acc)
+    }
+
+    /// Removes the constraints `i` for which `keep[i] == false`.
+    fn retain_constraints(&mut self, keep: &[bool]) {
+        for (i, _) in keep.iter().enumerate().filter(|(_, keep)| !**keep) {
+            for index in [
+                self.a_constraints[i],
+                self.b_constraints[i],
+                self.c_constraints[i],
+            ] {
+                if Some(index) != self.one_lc {
+                    self.lc_map.remove(&index);
+                }
+            }
+        }
+        retain_by_mask(&mut self.a_constraints, keep);
+        retain_by_mask(&mut self.b_constraints, keep);
+        retain_by_mask(&mut self.c_constraints, keep);
+        #[cfg(feature = "std")]
+        retain_by_mask(&mut self.constraint_traces, keep);
+        let mut new_indices = Vec::with_capacity(keep.len());
+        let mut num_kept = 0;
+        for &keep in keep {
+            new_indices.push(num_kept);
+            num_kept += keep as usize;
+        }
+        self.constraint_messages = ark_std::mem::take(&mut self.constraint_messages)
+            .into_iter()
+            .filter(|(i, _)| keep[*i])
+            .map(|(i, message)| (new_indices[i], message))
+            .collect();
+        self.num_constraints = self.a_constraints.len();
+    }
+
+    /// Rewrites every linear combination used in a constraint with `f`.
+    /// Linear combinations that are shared between constraints are rewritten
+    /// once.
+    fn map_constraint_lcs(&mut self, mut f: impl FnMut(&mut LinearCombination<F>)) {
+        let indices: BTreeSet<_> = self
+            .a_constraints
+            .iter()
+            .chain(&self.b_constraints)
+            .chain(&self.c_constraints)
+            .copied()
+            .collect();
+        for index in &indices {
+            if let Some(lc) = self.lc_map.get_mut(index) {
+                f(lc);
+            }
+        }
+    }
+
+    /// Removes satisfied constraints that only contain constants.
+    fn fold_constant_constraints(&mut self) {
+        let keep: Vec<_> = (0..self.num_constraints)
+            .map(|i| {
+                let a = self.constant_value(&self.a_constraints[i]);
+                let b = self.constant_value(&self.b_constraints[i]);
+                let c = self.constant_value(&self.c_constraints[i]);
+                match (a, b, c) {
+                    (Some(a), Some(b), Some(c)) => a * b != c,
+                    _ => true,
+                }
+            })
+            .collect();
+        self.retain_constraints(&keep);
+    }
+
+    /// Merges witness variables that are defined by constraints with equal
+    /// left-hand sides.
+    fn merge_equal_witnesses(&mut self) {
+        // `replacements[i] == Some(j)` if witness `i` is replaced by witness
+        // `j`. A witness may be replaced after its defining constraint was
+        // recorded in `definitions`, so replacements can form chains, which
+        // `root` follows to the witness that is not replaced.
+        let mut replacements = vec![None; self.num_witness_variables];
+        let mut is_target = vec![false; self.num_witness_variables];
+        for var in self.committed_witnesses.values().flatten() {
+            if let Variable::Witness(i) = var {
+                is_target[*i] = true;
+            }
+        }
+        let root = |mut i: usize, replacements: &[Option<usize>]| {
+            while let Some(j) = replacements[i] {
+                i = j;
+            }
+            i
+        };
+        let replace = |lc: &mut LinearCombination<F>, replacements: &[Option<usize>]| {
+            for (_, var) in lc.iter_mut() {
+                if let Variable::Witness(i) = var {
+                    *var = Variable::Witness(root(*i, replacements));
+                }
+            }
+            lc.compactify();
+        };
+
+        // Constraints defining a witness, bucketed by the variables of their
+        // left-hand sides, and then compared in full within a bucket.
+        let mut definitions = BTreeMap::<_, Vec<(usize, usize)>>::new();
+        let mut keep = vec![true; self.num_constraints];
+        for (i, keep) in keep.iter_mut().enumerate() {
+            for index in [
+                self.a_constraints[i],
+                self.b_constraints[i],
+                self.c_constraints[i],
+            ] {
+                replace(self.lc_map.get_mut(&index).unwrap(), &replacements);
+            }
+            let a = &self.lc_map[&self.a_constraints[i]];
+            let b = &self.lc_map[&self.b_constraints[i]];
+            let c = &self.lc_map[&self.c_constraints[i]];
+            let w = match c.as_slice() {
+                [(coeff, Variable::Witness(w))] if coeff.is_one() => *w,
+                _ => continue,
+            };
+            let w_var = Variable::Witness(w);
+            if is_target[w] || a.iter().chain(b.iter()).any(|(_, v)| *v == w_var) {
+                continue;
+            }
+
+            let key = (
+                a.iter().map(|(_, v)| *v).collect::<Vec<_>>(),
+                b.iter().map(|(_, v)| *v).collect::<Vec<_>>(),
+            );
+            let bucket = definitions.entry(key).or_default();
+            let existing = bucket.iter().find(|(j, _)| {
+                self.lc_map[&self.a_constraints[*j]] == *a
+                    && self.lc_map[&self.b_constraints[*j]] == *b
+            });
+            match existing.map(|&(_, target)| root(target, &replacements)) {
+                Some(target) if target != w => {
+                    replacements[w] = Some(target);
+                    is_target[target] = true;
+                    *keep = false;
+                },
+                Some(_) => {},
+                None => bucket.push((i, w)),
+            }
+        }
+        self.retain_constraints(&keep);
+        // Uses of replaced witnesses that precede their definition.
+        self.map_constraint_lcs(|lc| replace(lc, &replacements));
+    }
+
+    /// Removes witness variables that are not used in any constraint.
+    fn remove_unused_witnesses(&mut self) {
+        let mut is_used = vec![false; self.num_witness_variables];
+        for var in self.committed_witnesses.values().flatten() {
+            if let Variable::Witness(i) = var {
+                is_used[*i] = true;
+            }
+        }
+        self.map_constraint_lcs(|lc| {
+            lc.retain(|(coeff, _)| !coeff.is_zero());
+            for (_, var) in lc.iter() {
+                if let Variable::Witness(i) = var {
+                    is_used[*i] = true;
+                }
+            }
+        });
+
+        let mut new_indices = vec![0; self.num_witness_variables];
+        let mut num_used = 0;
+        for (new_index, &used) in new_indices.iter_mut().zip(&is_used) {
+            *new_index = num_used;
+            num_used += used as usize;
+        }
+        let renumber = |var: &mut Variable| {
+            if let Variable::Witness(i) = var {
+                *var = Variable::Witness(new_indices[*i]);
+            }
+        };
+        self.map_constraint_lcs(|lc| lc.iter_mut().for_each(|(_, var)| renumber(var)));
+        self.committed_witnesses
+            .values_mut()
+            .flatten()
+            .for_each(renumber);
+
+        // Linear combinations that are not used in a constraint may refer to
+        // removed witnesses.
+        let used_lcs: BTreeSet<_> = self
+            .a_constraints
+            .iter()
+            .chain(&self.b_constraints)
+            .chain(&self.c_constraints)
+            .copied()
+            .collect();
+        let one_lc = self.one_lc;
+        self.lc_map
+            .retain(|index, _| used_lcs.contains(index) || Some(*index) == one_lc);
+
+        if !self.is_in_setup_mode() {
+            retain_by_mask(&mut self.witness_assignment, &is_used);
+        }
+        self.num_witness_variables = num_used;
+    }
+
     /// This step must be called after constraint generation has completed, and
     /// after all symbolic LCs have been inlined into the places that they
     /// are used.
@@ -566,6 +1054,57 @@ impl<F: Field> ConstraintSystem<F> {
         }
     }
 
+    /// Computes statistics about the shape of the constraint matrices.
+    ///
+    /// Like `to_matrices`, this should be called after constraint generation
+    /// has completed and all symbolic LCs have been inlined. Returns `None` if
+    /// `self` does not construct matrices.
+    pub fn statistics(&self) -> Option<ConstraintSystemStatistics> {
+        if !self.should_construct_matrices() {
+            return None;
+        }
+        let mut max_lc_len = 0;
+        let mut num_non_zero = |constraints: &[LcIndex]| -> usize {
+            constraints
+                .iter()
+                .map(|index| {
+                    let len = self.lc_map[index]
+                        .iter()
+                        .filter(|(coeff, _)| !coeff.is_zero())
+                        .count();
+                    max_lc_len = max_lc_len.max(len);
+                    len
+                })
+                .sum()
+        };
+        let a_num_non_zero = num_non_zero(&self.a_constraints);
+        let b_num_non_zero = num_non_zero(&self.b_constraints);
+        let c_num_non_zero = num_non_zero(&self.c_constraints);
+
+        Some(ConstraintSystemStatistics {
+            num_instance_variables: self.num_instance_variables,
+            num_witness_variables: self.num_witness_variables,
+            num_constraints: self.num_constraints,
+            a_num_non_zero,
+            b_num_non_zero,
+            c_num_non_zero,
+            max_lc_len,
+        })
+    }
+
+    /// Computes a 32-byte digest of the constraint matrices and of the
+    /// number of instance and witness variables. The digest does not depend on
+    /// the variable assignments, so it is the same in setup and in proving
+    /// mode, and can be used to detect mismatches between a circuit and the
+    /// parameters generated for it.
+    ///
+    /// Like `to_matrices`, this should be called after constraint generation
+    /// has completed and all symbolic LCs have been inlined. Returns `None` if
+    /// `self` does not construct matrices.
+    pub fn circuit_digest(&self) -> Option<[u8; 32]> {
+        self.to_matrices().map(|matrices| matrices.digest())
+    }
+
     fn eval_lc(&self, lc: LcIndex) -> Option<F> {
         let lc = self.lc_map.get(&lc)?;
         let mut acc = F::zero();
@@ -616,6 +1155,9 @@ impl<F: Field> ConstraintSystem<F> {
                     {
                         trace = format!("{}", i);
                     }
+                    if let Some(message) = self.constraint_messages.get(&i) {
+                        return Ok(Some(format!("{} ({})", message, trace)));
+                    }
                     return Ok(Some(trace));
                 }
             }
@@ -631,7 +1173,7 @@ impl<F: Field> ConstraintSystem<F> {
             Variable::Witness(idx) => self.witness_assignment.get(idx).copied(),
             Variable::Instance(idx) => self.instance_assignment.get(idx).copied(),
             Variable::SymbolicLc(idx) => {
-                let value = self.lc_assignment_cache.borrow().get(&idx).copied();
+                let value = self.lc_assignment_cache.borrow().get(idx);
                 if value.is_some() {
                     value
                 } else {
@@ -643,6 +1185,13 @@ impl<F: Field> ConstraintSystem<F> {
         }
     }
 }
+
+/// Retains the elements `v[i]` for which `keep[i] == true`.
+fn retain_by_mask<T>(v: &mut Vec<T>, keep: &[bool]) {
+    let mut keep = keep.iter();
+    v.retain(|_| *keep.next().unwrap());
+}
+
 /// The A, B and C matrices of a Rank-One `ConstraintSystem`.
 /// Also contains metadata on the structure of the constraint system
 /// and the matrices.
@@ -674,6 +1223,261 @@ pub struct ConstraintMatrices<F: Field> {
     pub c: Matrix<F>,
 }
 
+/// Statistics about the shape of a Rank-One `ConstraintSystem`, as computed by
+/// `ConstraintSystem::statistics`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ConstraintSystemStatistics {
+    /// The number of variables that are "public instances" to the constraint
+    /// system, including the constant `one`.
+    pub num_instance_variables: usize,
+    /// The number of variables that are "private witnesses" to the constraint
+    /// system.
+    pub num_witness_variables: usize,
+    /// The number of constraints in the constraint system.
+    pub num_constraints: usize,
+    /// The number of non_zero entries in the A matrix.
+    pub a_num_non_zero: usize,
+    /// The number of non_zero entries in the B matrix.
+    pub b_num_non_zero: usize,
+    /// The number of non_zero entries in the C matrix.
+    pub c_num_non_zero: usize,
+    /// The largest number of non_zero entries in a single row of the A, B, or
+    /// C matrix.
+    pub max_lc_len: usize,
+}
+
+impl ConstraintSystemStatistics {
+    /// The total number of variables, including the constant `one`.
+    pub fn num_variables(&self) -> usize {
+        self.num_instance_variables + self.num_witness_variables
+    }
+
+    /// The total number of non_zero entries across the A, B, and C matrices.
+    pub fn num_non_zero(&self) -> usize {
+        self.a_num_non_zero + self.b_num_non_zero + self.c_num_non_zero
+    }
+
+    /// The average number of non_zero entries in a row of the A, B, or C
+    /// matrix.
+    pub fn average_lc_len(&self) -> f64 {
+        if self.num_constraints == 0 {
+            0.0
+        } else {
+            self.num_non_zero() as f64 / (3 * self.num_constraints) as f64
+        }
+    }
+
+    /// The fraction of entries of the A, B, and C matrices that are non_zero.
+    pub fn density(&self) -> f64 {
+        let num_entries = 3 * self.num_constraints * self.num_variables();
+        if num_entries == 0 {
+            0.0
+        } else {
+            self.num_non_zero() as f64 / num_entries as f64
+        }
+    }
+
+    /// Serializes `self` as a JSON object.
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"num_instance_variables\":{},\"num_witness_variables\":{},\"num_constraints\":{},\
+             \"a_num_non_zero\":{},\"b_num_non_zero\":{},\"c_num_non_zero\":{},\
+             \"max_lc_len\":{},\"average_lc_len\":{},\"density\":{}}}",
+            self.num_instance_variables,
+            self.num_witness_variables,
+            self.num_constraints,
+            self.a_num_non_zero,
+            self.b_num_non_zero,
+            self.c_num_non_zero,
+            self.max_lc_len,
+            self.average_lc_len(),
+            self.density(),
+        )
+    }
+}
+
+impl<F: Field> ConstraintMatrices<F> {
+    /// Computes a 32-byte BLAKE2s digest of the matrices and of the number of
+    /// instance and witness variables.
+    ///
+    /// Rows are normalized before hashing (entries are sorted by variable,
+    /// duplicate variables are merged, and zero entries are dropped), so
+    /// matrices that represent the same constraints have the same digest.
+    pub fn digest(&self) -> [u8; 32] {
+        use blake2::{Blake2s256, Digest};
+
+        let mut hasher = Blake2s256::new();
+        hasher.update(b"ark-relations/circuit-digest/v1");
+        for n in [
+            self.num_instance_variables,
+            self.num_witness_variables,
+            self.num_constraints,
+        ] {
+            hasher.update((n as u64).to_le_bytes());
+        }
+
+        let mut buffer = Vec::new();
+        for matrix in [&self.a, &self.b, &self.c] {
+            hasher.update((matrix.len() as u64).to_le_bytes());
+            for row in matrix {
+                let mut normalized = BTreeMap::new();
+                for &(coeff, index) in row {
+                    *normalized.entry(index).or_insert_with(F::zero) += coeff;
+                }
+                normalized.retain(|_, coeff| !coeff.is_zero());
+
+                hasher.update((normalized.len() as u64).to_le_bytes());
+                for (index, coeff) in normalized {
+                    hasher.update((index as u64).to_le_bytes());
+                    buffer.clear();
+                    coeff
+                        .serialize_compressed(&mut buffer)
+                        .expect("serializing to a vector cannot fail");
+                    hasher.update(&buffer);
+                }
+            }
+        }
+
+        let mut digest = [0u8; 32];
+        digest.copy_from_slice(&hasher.finalize());
+        digest
+    }
+
+    /// Returns the indices, in increasing order, of the constraints whose row
+    /// in A or in B is the constant one. These constraints are linear in the
+    /// variables.
+    pub fn linear_constraints(&self) -> Vec<usize> {
+        let one = [(F::one(), 0)];
+        self.a
+            .iter()
+            .zip(&self.b)
+            .enumerate()
+            .filter(|(_, (a, b))| a[..] == one || b[..] == one)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Converts the A, B, and C matrices to compressed sparse row format.
+    /// Every matrix has `self.num_instance_variables +
+    /// self.num_witness_variables` columns.
+    pub fn to_csr(&self) -> (CsrMatrix<F>, CsrMatrix<F>, CsrMatrix<F>) {
+        let num_cols = self.num_instance_variables + self.num_witness_variables;
+        (
+            CsrMatrix::from_matrix(&self.a, num_cols),
+            CsrMatrix::from_matrix(&self.b, num_cols),
+            CsrMatrix::from_matrix(&self.c, num_cols),
+        )
+    }
+}
+
+/// A sparse matrix in compressed sparse row (CSR) format.
+///
+/// The entries of row `i` are `values[row_ptr[i]..row_ptr[i + 1]]`, and lie in
+/// the columns `col_indices[row_ptr[i]..row_ptr[i + 1]]`. Unlike `Matrix`,
+/// all entries are stored contiguously.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsrMatrix<F: Field> {
+    /// The number of rows.
+    pub num_rows: usize,
+    /// The number of columns.
+    pub num_cols: usize,
+    /// The entries of the matrix, row by row.
+    pub values: Vec<F>,
+    /// The column of each entry in `values`.
+    pub col_indices: Vec<usize>,
+    /// The offset in `values` of the first entry of each row, followed by
+    /// `values.len()`.
+    pub row_ptr: Vec<usize>,
+}
+
+impl<F: Field> CsrMatrix<F> {
+    /// Converts `matrix`, which has `num_cols` columns, to CSR format. The
+    /// entries of each row are kept in their original order.
+    ///
+    /// # Panics
+    /// This method panics if an entry of `matrix` lies in a column
+    /// `>= num_cols`.
+    pub fn from_matrix(matrix: &Matrix<F>, num_cols: usize) -> Self {
+        let num_non_zero = matrix.iter().map(Vec::len).sum();
+        let mut values = Vec::with_capacity(num_non_zero);
+        let mut col_indices = Vec::with_capacity(num_non_zero);
+        let mut row_ptr = Vec::with_capacity(matrix.len() + 1);
+        row_ptr.push(0);
+        for row in matrix {
+            for &(coeff, col) in row {
+                assert!(col < num_cols, "column {} is out of bounds", col);
+                values.push(coeff);
+                col_indices.push(col);
+            }
+            row_ptr.push(values.len());
+        }
+        Self {
+            num_rows: matrix.len(),
+            num_cols,
+            values,
+            col_indices,
+            row_ptr,
+        }
+    }
+
+    /// The number of stored entries.
+    pub fn num_non_zero(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns an iterator over the `(coefficient, column)` entries of row
+    /// `i`.
+    pub fn row(&self, i: usize) -> impl Iterator<Item = (F, usize)> + '_ {
+        let range = self.row_ptr[i]..self.row_ptr[i + 1];
+        self.values[range.clone()]
+            .iter()
+            .copied()
+            .zip(self.col_indices[range].iter().copied())
+    }
+
+    /// Returns the transpose of `self`, i.e., `self` in compressed sparse
+    /// column format. The entries of each row of the transpose are sorted by
+    /// column.
+    pub fn transpose(&self) -> Self {
+        let mut row_ptr = vec![0; self.num_cols + 1];
+        for &col in &self.col_indices {
+            row_ptr[col + 1] += 1;
+        }
+        for i in 0..self.num_cols {
+            row_ptr[i + 1] += row_ptr[i];
+        }
+
+        let mut next = row_ptr.clone();
+        let mut values = vec![F::zero(); self.num_non_zero()];
+        let mut col_indices = vec![0; self.num_non_zero()];
+        for row in 0..self.num_rows {
+            for (coeff, col) in self.row(row) {
+                values[next[col]] = coeff;
+                col_indices[next[col]] = row;
+                next[col] += 1;
+            }
+        }
+        Self {
+            num_rows: self.num_cols,
+            num_cols: self.num_rows,
+            values,
+            col_indices,
+            row_ptr,
+        }
+    }
+
+    /// Computes the matrix-vector product `self ⋅ z`.
+    ///
+    /// # Panics
+    /// This method panics if `z.len() != self.num_cols`.
+    pub fn mul_vector(&self, z: &[F]) -> Vec<F> {
+        assert_eq!(z.len(), self.num_cols);
+        (0..self.num_rows)
+            .map(|i| self.row(i).map(|(coeff, col)| coeff * z[col]).sum())
+            .collect()
+    }
+}
+
 /// A shared reference to a constraint system that can be stored in high level
 /// variables.
 #[derive(Debug, Clone)]
@@ -703,21 +1507,62 @@ impl<F: Field> Eq for ConstraintSystemRef<F> {}
 pub struct Namespace<F: Field> {
     inner: ConstraintSystemRef<F>,
     id: Option<tracing::Id>,
+    name: &'static str,
+    budget: Option<Rc<BudgetGuard<F>>>,
+}
+
+/// Removes a constraint budget from the constraint system when the last clone
+/// of the `Namespace` that holds it is dropped.
+#[derive(Debug)]
+struct BudgetGuard<F: Field> {
+    cs: Rc<RefCell<ConstraintSystem<F>>>,
+    id: usize,
+}
+
+impl<F: Field> Drop for BudgetGuard<F> {
+    fn drop(&mut self) {
+        if let Ok(mut cs) = self.cs.try_borrow_mut() {
+            cs.remove_constraint_budget(self.id);
+        }
+    }
 }
 
 impl<F: Field> From<ConstraintSystemRef<F>> for Namespace<F> {
     fn from(other: ConstraintSystemRef<F>) -> Self {
-        Self {
-            inner: other,
-            id: None,
-        }
+        Self::new(other, None)
     }
 }
 
 impl<F: Field> Namespace<F> {
     /// Construct a new `Namespace`.
     pub fn new(inner: ConstraintSystemRef<F>, id: Option<tracing::Id>) -> Self {
-        Self { inner, id }
+        Self {
+            inner,
+            id,
+            name: "<unnamed>",
+            budget: None,
+        }
+    }
+
+    /// Attach the name `name` to `self`. The name is used in error messages,
+    /// such as `SynthesisError::BudgetExceeded`.
+    pub fn named(mut self, name: &'static str) -> Self {
+        self.name = name;
+        self
+    }
+
+    /// Limit the number of constraints that may be enforced while `self` (or
+    /// a clone of it) is alive to `budget`. Enforcing more constraints makes
+    /// `enforce_constraint` return `SynthesisError::BudgetExceeded`.
+    ///
+    /// Budgets of nested namespaces are checked independently, so a
+    /// sub-gadget is limited by the smallest remaining budget around it.
+    pub fn with_constraint_budget(mut self, budget: usize) -> Self {
+        if let ConstraintSystemRef::CS(cs) = &self.inner {
+            let id = cs.borrow_mut().add_constraint_budget(self.name, budget);
+            self.budget = Some(Rc::new(BudgetGuard { cs: cs.clone(), id }));
+        }
+        self
     }
 
     /// Obtain the inner `ConstraintSystemRef<F>`.
@@ -777,6 +1622,19 @@ impl<F: Field> ConstraintSystemRef<F> {
         }
     }
 
+    /// Returns a reference to an independent copy of the underlying
+    /// `ConstraintSystem`, as produced by `ConstraintSystem::snapshot`.
+    ///
+    /// Variables allocated before the snapshot are valid in the copy, but
+    /// gadgets that hold `self` must be rebuilt with the returned reference
+    /// before they are used to extend the copy.
+    pub fn snapshot(&self) -> Self {
+        match self {
+            Self::CS(cs) => Self::new(cs.borrow().snapshot()),
+            Self::None => Self::None,
+        }
+    }
+
     /// Obtain an immutable reference to the underlying `ConstraintSystem`.
     ///
     /// # Panics
@@ -891,6 +1749,39 @@ impl<F: Field> ConstraintSystemRef<F> {
             })
     }
 
+    /// Obtain a variable representing a new private witness input that belongs
+    /// to the externally committed block `block_id`.
+    #[inline]
+    pub fn new_committed_witness_variable<Func>(
+        &self,
+        block_id: usize,
+        f: Func,
+    ) -> crate::r1cs::Result<Variable>
+    where
+        Func: FnOnce() -> crate::r1cs::Result<F>,
+    {
+        self.inner()
+            .ok_or(SynthesisError::MissingCS)
+            .and_then(|cs| {
+                if !self.is_in_setup_mode() {
+                    // This is needed to avoid double-borrows, because `f`
+                    // might itself mutably borrow `cs` (eg: `f = || g.value()`).
+                    let value = f();
+                    cs.borrow_mut()
+                        .new_committed_witness_variable(block_id, || value)
+                } else {
+                    cs.borrow_mut().new_committed_witness_variable(block_id, f)
+                }
+            })
+    }
+
+    /// The witness variables of each externally committed block, indexed by
+    /// block id, in the order in which they were allocated.
+    pub fn committed_blocks(&self) -> BTreeMap<usize, Vec<Variable>> {
+        self.inner()
+            .map_or(BTreeMap::new(), |cs| cs.borrow().committed_blocks().clone())
+    }
+
     /// Obtain a variable representing a linear combination.
     #[inline]
     pub fn new_lc(&self, lc: LinearCombination<F>) -> crate::r1cs::Result<Variable> {
@@ -912,6 +1803,34 @@ impl<F: Field> ConstraintSystemRef<F> {
             .and_then(|cs| cs.borrow_mut().enforce_constraint(a, b, c))
     }
 
+    /// Enforce the linear constraint `lc = 0`.
+    /// See `ConstraintSystem::enforce_linear_constraint` for details.
+    #[inline]
+    pub fn enforce_linear_constraint(&self, lc: LinearCombination<F>) -> crate::r1cs::Result<()> {
+        self.inner()
+            .ok_or(SynthesisError::MissingCS)
+            .and_then(|cs| cs.borrow_mut().enforce_linear_constraint(lc))
+    }
+
+    /// Enforce a R1CS constraint, labelled with the human-readable `message`.
+    /// If this constraint is the first unsatisfied one, `which_is_unsatisfied`
+    /// reports `message` alongside the constraint trace.
+    #[inline]
+    pub fn enforce_constraint_with_message(
+        &self,
+        a: LinearCombination<F>,
+        b: LinearCombination<F>,
+        c: LinearCombination<F>,
+        message: &'static str,
+    ) -> crate::r1cs::Result<()> {
+        self.inner()
+            .ok_or(SynthesisError::MissingCS)
+            .and_then(|cs| {
+                cs.borrow_mut()
+                    .enforce_constraint_with_message(a, b, c, message)
+            })
+    }
+
     /// Naively inlines symbolic linear combinations into the linear
     /// combinations that use them.
     ///
@@ -934,6 +1853,18 @@ impl<F: Field> ConstraintSystemRef<F> {
         }
     }
 
+    /// Finalize the constraint system for `goal`, and then remove constant
+    /// constraints, duplicated witnesses, and unused witnesses.
+    /// See `ConstraintSystem::optimize` for details.
+    ///
+    /// # Panics
+    /// This method panics if `self` does not construct matrices.
+    pub fn optimize(&self, goal: OptimizationGoal) {
+        if let Some(cs) = self.inner() {
+            cs.borrow_mut().optimize(goal)
+        }
+    }
+
     /// This step must be called after constraint generation has completed, and
     /// after all symbolic LCs have been inlined into the places that they
     /// are used.
@@ -942,6 +1873,27 @@ impl<F: Field> ConstraintSystemRef<F> {
         self.inner().and_then(|cs| cs.borrow().to_matrices())
     }
 
+    /// Computes a 32-byte digest of the constraint matrices and of the
+    /// number of instance and witness variables.
+    /// See `ConstraintSystem::circuit_digest` for details.
+    ///
+    /// Returns `None` if `self == None` or if `self` does not construct
+    /// matrices.
+    #[inline]
+    pub fn circuit_digest(&self) -> Option<[u8; 32]> {
+        self.inner().and_then(|cs| cs.borrow().circuit_digest())
+    }
+
+    /// Computes statistics about the shape of the constraint matrices.
+    ///
+    /// Like `to_matrices`, this should be called after constraint generation
+    /// has completed and all symbolic LCs have been inlined. Returns `None` if
+    /// `self == None` or if `self` does not construct matrices.
+    #[inline]
+    pub fn statistics(&self) -> Option<ConstraintSystemStatistics> {
+        self.inner().and_then(|cs| cs.borrow().statistics())
+    }
+
     /// If `self` is satisfied, outputs `Ok(true)`.
     /// If `self` is unsatisfied, outputs `Ok(false)`.
     /// If `self.is_in_setup_mode()` or if `self == None`, outputs `Err(())`.
@@ -1022,7 +1974,7 @@ impl<F: Field> ConstraintSystemRef<F> {
 
 #[cfg(test)]
 mod tests {
-    use crate::r1cs::*;
+    use crate::{ns, r1cs::*};
     use ark_ff::One;
     use ark_test_curves::bls12_381::Fr;
 
@@ -1054,6 +2006,67 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn statistics() -> crate::r1cs::Result<()> {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let two = Fr::one() + Fr::one();
+        let a = cs.new_input_variable(|| Ok(Fr::one()))?;
+        let b = cs.new_witness_variable(|| Ok(Fr::one()))?;
+        let c = cs.new_witness_variable(|| Ok(two))?;
+        cs.enforce_constraint(lc!() + a, lc!() + (two, b), lc!() + c)?;
+        let d = cs.new_lc(lc!() + a + b)?;
+        cs.enforce_constraint(lc!() + a, lc!() + d, lc!() + d)?;
+        cs.inline_all_lcs();
+
+        let stats = cs.statistics().unwrap();
+        let matrices = cs.to_matrices().unwrap();
+        assert_eq!(stats.num_instance_variables, 2);
+        assert_eq!(stats.num_witness_variables, 2);
+        assert_eq!(stats.num_constraints, 2);
+        assert_eq!(stats.a_num_non_zero, matrices.a_num_non_zero);
+        assert_eq!(stats.b_num_non_zero, matrices.b_num_non_zero);
+        assert_eq!(stats.c_num_non_zero, matrices.c_num_non_zero);
+        assert_eq!(stats.max_lc_len, 2);
+        assert_eq!(stats.num_non_zero(), 8);
+        assert_eq!(stats.density(), 8.0 / 24.0);
+        assert!(stats
+            .to_json()
+            .starts_with("{\"num_instance_variables\":2,"));
+
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        cs.set_mode(SynthesisMode::Prove {
+            construct_matrices: false,
+        });
+        assert!(cs.statistics().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn circuit_digest() -> crate::r1cs::Result<()> {
+        let digest = |mode, coeff: Fr, public: bool| -> crate::r1cs::Result<[u8; 32]> {
+            let cs = ConstraintSystem::<Fr>::new_ref();
+            cs.set_mode(mode);
+            let a = if public {
+                cs.new_input_variable(|| Ok(Fr::one()))?
+            } else {
+                cs.new_witness_variable(|| Ok(Fr::one()))?
+            };
+            let b = cs.new_witness_variable(|| Ok(Fr::one()))?;
+            let c = cs.new_lc(lc!() + a + b)?;
+            cs.enforce_constraint(lc!() + c, lc!() + (coeff, b), lc!() + c)?;
+            cs.finalize();
+            Ok(cs.circuit_digest().unwrap())
+        };
+        let prove = SynthesisMode::Prove {
+            construct_matrices: true,
+        };
+        let expected = digest(SynthesisMode::Setup, Fr::one(), true)?;
+        assert_eq!(digest(prove, Fr::one(), true)?, expected);
+        assert_ne!(digest(prove, Fr::one() + Fr::one(), true)?, expected);
+        assert_ne!(digest(prove, Fr::one(), false)?, expected);
+        Ok(())
+    }
+
     #[test]
     fn matrix_generation_outlined() -> crate::r1cs::Result<()> {
         let cs = ConstraintSystem::<Fr>::new_ref();
@@ -1090,6 +2103,376 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn optimize() -> crate::r1cs::Result<()> {
+        for mode in [
+            SynthesisMode::Setup,
+            SynthesisMode::Prove {
+                construct_matrices: true,
+            },
+        ] {
+            let cs = ConstraintSystem::<Fr>::new_ref();
+            cs.set_mode(mode);
+            let out = cs.new_input_variable(|| Ok(Fr::from(8u8)))?;
+            let x = cs.new_witness_variable(|| Ok(Fr::from(2u8)))?;
+            let _unused = cs.new_witness_variable(|| Ok(Fr::from(7u8)))?;
+            let y = cs.new_witness_variable(|| Ok(Fr::from(4u8)))?;
+            let y2 = cs.new_witness_variable(|| Ok(Fr::from(4u8)))?;
+            cs.enforce_constraint(lc!() + y2, lc!() + x, lc!() + out)?;
+            cs.enforce_constraint(lc!() + x, lc!() + x, lc!() + y)?;
+            cs.enforce_constraint(lc!() + x, lc!() + x, lc!() + y2)?;
+            let one = cs.new_lc(lc!() + Variable::One)?;
+            cs.enforce_constraint(lc!() + one, lc!() + one, lc!() + Variable::One)?;
+
+            cs.optimize(OptimizationGoal::Constraints);
+            assert_eq!(cs.num_constraints(), 2);
+            assert_eq!(cs.num_witness_variables(), 2);
+            if !cs.is_in_setup_mode() {
+                assert!(cs.is_satisfied()?);
+            }
+            // Variables: one, out, x, y.
+            let matrices = cs.to_matrices().unwrap();
+            assert_eq!(matrices.a[0], vec![(Fr::one(), 3)]);
+            assert_eq!(matrices.b[0], vec![(Fr::one(), 2)]);
+            assert_eq!(matrices.c[0], vec![(Fr::one(), 1)]);
+            assert_eq!(matrices.a[1], vec![(Fr::one(), 2)]);
+            assert_eq!(matrices.b[1], vec![(Fr::one(), 2)]);
+            assert_eq!(matrices.c[1], vec![(Fr::one(), 3)]);
+        }
+
+        // Unsatisfied constant constraints are kept.
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        cs.enforce_constraint(
+            lc!() + Variable::One,
+            lc!() + Variable::One,
+            lc!() + (Fr::from(2u8), Variable::One),
+        )?;
+        cs.optimize(OptimizationGoal::Constraints);
+        assert_eq!(cs.num_constraints(), 1);
+        assert!(!cs.is_satisfied()?);
+        Ok(())
+    }
+
+    #[test]
+    fn optimize_merges_chains() -> crate::r1cs::Result<()> {
+        // `b` is merged into `a`, after which `c` is merged into `b`, so `c`
+        // must end up merged into `a`. The assignment only satisfies the
+        // constraints if `z * z` is not tied to `y * y`.
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let x = cs.new_witness_variable(|| Ok(Fr::from(2u8)))?;
+        let y = cs.new_witness_variable(|| Ok(Fr::from(2u8)))?;
+        let z = cs.new_witness_variable(|| Ok(Fr::from(3u8)))?;
+        let a = cs.new_witness_variable(|| Ok(Fr::from(4u8)))?;
+        let b = cs.new_witness_variable(|| Ok(Fr::from(9u8)))?;
+        let c = cs.new_witness_variable(|| Ok(Fr::from(9u8)))?;
+        cs.enforce_constraint(lc!() + y, lc!() + y, lc!() + b)?;
+        cs.enforce_constraint(lc!() + x, lc!() + x, lc!() + a)?;
+        cs.enforce_constraint(lc!() + x, lc!() + x, lc!() + b)?;
+        cs.enforce_constraint(lc!() + z, lc!() + z, lc!() + c)?;
+        cs.enforce_constraint(lc!() + y, lc!() + y, lc!() + c)?;
+        assert!(!cs.is_satisfied()?);
+
+        cs.optimize(OptimizationGoal::Constraints);
+        assert_eq!(cs.num_constraints(), 3);
+        assert!(!cs.is_satisfied()?);
+        let matrices = cs.to_matrices().unwrap();
+        assert!(matrices.c.iter().all(|row| *row == matrices.c[0]));
+        Ok(())
+    }
+
+    #[test]
+    fn optimize_then_finalize() -> crate::r1cs::Result<()> {
+        for goal in [OptimizationGoal::Constraints, OptimizationGoal::Weight] {
+            let cs = ConstraintSystem::<Fr>::new_ref();
+            cs.set_optimization_goal(goal);
+            let out = cs.new_input_variable(|| Ok(Fr::from(36u8)))?;
+            let x = cs.new_witness_variable(|| Ok(Fr::from(2u8)))?;
+            let _unused = cs.new_witness_variable(|| Ok(Fr::from(7u8)))?;
+            let y = cs.new_witness_variable(|| Ok(Fr::from(4u8)))?;
+            let s = cs.new_lc(lc!() + x + y)?;
+            cs.enforce_constraint(lc!() + s, lc!() + s, lc!() + out)?;
+            cs.enforce_constraint(lc!() + x, lc!() + x, lc!() + y)?;
+            cs.enforce_constraint(
+                lc!() + Variable::One,
+                lc!() + Variable::One,
+                lc!() + Variable::One,
+            )?;
+
+            cs.optimize(goal);
+            let matrices = cs.to_matrices().unwrap();
+            cs.finalize();
+            cs.optimize(goal);
+            assert_eq!(cs.to_matrices().unwrap(), matrices);
+            assert!(cs.is_satisfied()?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn optimize_without_matrices() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        cs.set_mode(SynthesisMode::Prove {
+            construct_matrices: false,
+        });
+        cs.optimize(OptimizationGoal::Constraints);
+    }
+
+    #[test]
+    fn committed_witnesses() -> crate::r1cs::Result<()> {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let unused = cs.new_witness_variable(|| Ok(Fr::one()))?;
+        let x = cs.new_committed_witness_variable(1, || Ok(Fr::from(2u8)))?;
+        let y = cs.new_committed_witness_variable(0, || Ok(Fr::from(3u8)))?;
+        let z = cs.new_committed_witness_variable(1, || Ok(Fr::from(6u8)))?;
+        let w = cs.new_witness_variable(|| Ok(Fr::from(6u8)))?;
+        cs.enforce_constraint(lc!() + x, lc!() + y, lc!() + w)?;
+        assert_eq!(unused, Variable::Witness(0));
+        let blocks = cs.committed_blocks();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[&0], vec![y]);
+        assert_eq!(blocks[&1], vec![x, z]);
+
+        // `z` is unused, but committed witnesses are never removed.
+        cs.optimize(OptimizationGoal::Constraints);
+        assert_eq!(cs.num_witness_variables(), 4);
+        let blocks = cs.committed_blocks();
+        assert_eq!(blocks[&0], vec![Variable::Witness(1)]);
+        assert_eq!(blocks[&1], vec![Variable::Witness(0), Variable::Witness(2)]);
+        assert_eq!(cs.assigned_value(blocks[&1][1]), Some(Fr::from(6u8)));
+        assert!(cs.is_satisfied()?);
+        Ok(())
+    }
+
+    #[test]
+    fn constraint_messages() -> crate::r1cs::Result<()> {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let a = cs.new_witness_variable(|| Ok(Fr::one()))?;
+        let b = cs.new_witness_variable(|| Ok(Fr::one() + Fr::one()))?;
+        let one = cs.new_lc(lc!() + Variable::One)?;
+        cs.enforce_constraint(lc!() + one, lc!() + one, lc!() + Variable::One)?;
+        cs.enforce_constraint_with_message(
+            lc!() + a,
+            lc!() + Variable::One,
+            lc!() + a,
+            "a must equal a",
+        )?;
+        cs.enforce_constraint_with_message(
+            lc!() + a,
+            lc!() + Variable::One,
+            lc!() + b,
+            "a must equal b",
+        )?;
+        cs.optimize(OptimizationGoal::Constraints);
+        // The constant constraint was removed, so the messages were shifted.
+        assert_eq!(cs.num_constraints(), 2);
+        let unsatisfied = cs.which_is_unsatisfied()?.unwrap();
+        assert!(unsatisfied.starts_with("a must equal b ("));
+        Ok(())
+    }
+
+    #[test]
+    fn constraint_budgets() -> crate::r1cs::Result<()> {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let a = cs.new_witness_variable(|| Ok(Fr::one()))?;
+        let enforce =
+            |cs: &ConstraintSystemRef<Fr>| cs.enforce_constraint(lc!() + a, lc!() + a, lc!() + a);
+        enforce(&cs)?;
+        {
+            let outer = ns!(cs, "outer").with_constraint_budget(3);
+            let cs = outer.cs();
+            enforce(&cs)?;
+            {
+                let inner = ns!(cs, "inner").with_constraint_budget(1);
+                let cs = inner.cs();
+                enforce(&cs)?;
+                assert_eq!(
+                    enforce(&cs),
+                    Err(SynthesisError::BudgetExceeded {
+                        ns: "inner",
+                        used: 2,
+                        budget: 1,
+                    })
+                );
+            }
+            enforce(&cs)?;
+            assert_eq!(
+                enforce(&cs),
+                Err(SynthesisError::BudgetExceeded {
+                    ns: "outer",
+                    used: 4,
+                    budget: 3,
+                })
+            );
+        }
+        // Budgets no longer apply once their namespace is dropped.
+        enforce(&cs)?;
+        assert_eq!(cs.num_constraints(), 5);
+        Ok(())
+    }
+
+    #[test]
+    fn snapshot() -> crate::r1cs::Result<()> {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let x = cs.new_witness_variable(|| Ok(Fr::from(3u8)))?;
+        let x_sq = cs.new_witness_variable(|| Ok(Fr::from(9u8)))?;
+        cs.enforce_constraint(lc!() + x, lc!() + x, lc!() + x_sq)?;
+        let sum = cs.new_lc(lc!() + x_sq + x)?;
+        assert_eq!(cs.assigned_value(sum), Some(Fr::from(12u8)));
+
+        let resume = |out: u8| -> crate::r1cs::Result<_> {
+            let cs = cs.snapshot();
+            let out = cs.new_input_variable(|| Ok(Fr::from(out)))?;
+            cs.enforce_constraint(lc!() + sum, lc!() + Variable::One, lc!() + out)?;
+            cs.finalize();
+            Ok(cs)
+        };
+        let good = resume(12)?;
+        let bad = resume(13)?;
+        assert!(good.is_satisfied()?);
+        assert!(!bad.is_satisfied()?);
+        assert_eq!(good.num_constraints(), 2);
+        assert_eq!(cs.num_constraints(), 1);
+        assert_eq!(cs.num_instance_variables(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn csr_matrices() -> crate::r1cs::Result<()> {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let out = cs.new_input_variable(|| Ok(Fr::from(12u8)))?;
+        let x = cs.new_witness_variable(|| Ok(Fr::from(3u8)))?;
+        let y = cs.new_witness_variable(|| Ok(Fr::from(4u8)))?;
+        cs.enforce_constraint(lc!() + x, lc!() + y, lc!() + out)?;
+        cs.enforce_constraint(lc!() + x + y, lc!() + Variable::One, lc!() + y + x)?;
+        cs.finalize();
+        let matrices = cs.to_matrices().unwrap();
+        let (a, b, c) = matrices.to_csr();
+        assert_eq!(a.row_ptr, vec![0, 1, 3]);
+        assert_eq!(b.num_non_zero(), 2);
+
+        let cs = cs.borrow().unwrap();
+        let z = [&cs.instance_assignment[..], &cs.witness_assignment[..]].concat();
+        let (az, bz, cz) = (a.mul_vector(&z), b.mul_vector(&z), c.mul_vector(&z));
+        for i in 0..matrices.num_constraints {
+            assert_eq!(az[i] * bz[i], cz[i]);
+        }
+
+        let a_t = a.transpose();
+        assert_eq!((a_t.num_rows, a_t.num_cols), (4, 2));
+        // Only the witnesses `x` and `y` (columns 2 and 3) appear in A.
+        assert_eq!(a_t.row_ptr, vec![0, 0, 0, 2, 3]);
+        assert_eq!(
+            a_t.row(2).collect::<Vec<_>>(),
+            vec![(Fr::one(), 0), (Fr::one(), 1)]
+        );
+        assert_eq!(a_t.transpose(), a);
+        Ok(())
+    }
+
+    #[test]
+    fn linear_constraints() -> crate::r1cs::Result<()> {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let out = cs.new_input_variable(|| Ok(Fr::from(7u8)))?;
+        let x = cs.new_witness_variable(|| Ok(Fr::from(3u8)))?;
+        let y = cs.new_witness_variable(|| Ok(Fr::from(4u8)))?;
+        let x_plus_y = cs.new_lc(lc!() + x + y)?;
+        cs.enforce_linear_constraint(lc!() + x_plus_y - out)?;
+        cs.enforce_constraint(
+            lc!() + x,
+            lc!() + y,
+            lc!() + (Fr::from(12u8), Variable::One),
+        )?;
+        cs.enforce_constraint(lc!() + Variable::One, lc!() + out, lc!() + x + y)?;
+        // The constant side of both linear constraints is shared.
+        assert_eq!(cs.borrow().unwrap().lc_map.len(), 9);
+        cs.finalize();
+        assert!(cs.is_satisfied()?);
+
+        let matrices = cs.to_matrices().unwrap();
+        assert_eq!(matrices.linear_constraints(), vec![0, 2]);
+        assert_eq!(matrices.b[0], vec![(Fr::one(), 0)]);
+        assert_eq!(matrices.a[2], vec![(Fr::one(), 0)]);
+
+        cs.borrow_mut().unwrap().witness_assignment[0] = Fr::from(2u8);
+        assert!(!cs.is_satisfied()?);
+        Ok(())
+    }
+
+    #[test]
+    fn lc_assignment_cache() -> crate::r1cs::Result<()> {
+        let mut cache = super::LcAssignmentCache::<Fr>::new();
+        cache.register(LcIndex(3));
+        cache.register(LcIndex(1000));
+        assert_eq!(cache.get(LcIndex(1000)), None);
+        cache.insert(LcIndex(1000), Fr::from(7u8));
+        assert_eq!(cache.get(LcIndex(1000)), Some(Fr::from(7u8)));
+        assert_eq!(cache.get(LcIndex(3)), None);
+        // Only symbolic LCs get a slot.
+        assert_eq!(cache.values.len(), 2);
+        cache.insert(LcIndex(5), Fr::from(7u8));
+        assert_eq!(cache.get(LcIndex(5)), None);
+        cache.invalidate();
+        assert_eq!(cache.get(LcIndex(1000)), None);
+
+        // Clones do not share their cache, so LCs allocated by each clone
+        // under the same index do not collide.
+        let mut cs = ConstraintSystem::<Fr>::new();
+        let x = cs.new_witness_variable(|| Ok(Fr::from(3u8)))?;
+        let mut other = cs.clone();
+        let two_x = cs.new_lc(lc!() + x + x)?;
+        let three_x = other.new_lc(lc!() + x + x + x)?;
+        assert_eq!(two_x, three_x);
+        assert_eq!(cs.assigned_value(two_x), Some(Fr::from(6u8)));
+        assert_eq!(other.assigned_value(three_x), Some(Fr::from(9u8)));
+
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let x = cs.new_witness_variable(|| Ok(Fr::from(3u8)))?;
+        let two_x = cs.new_lc(lc!() + x + x)?;
+        let four_x = cs.new_lc(lc!() + two_x + two_x)?;
+        assert_eq!(cs.assigned_value(four_x), Some(Fr::from(12u8)));
+        assert_eq!(
+            cs.borrow()
+                .unwrap()
+                .lc_assignment_cache
+                .borrow()
+                .get(LcIndex(0)),
+            Some(Fr::from(6u8))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn error_context() -> crate::r1cs::Result<()> {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        cs.new_witness_variable(|| Ok(Fr::one()))?;
+        // Allocation does not attach context by itself.
+        let err = cs
+            .new_witness_variable(|| Err(SynthesisError::AssignmentMissing))
+            .unwrap_err();
+        assert_eq!(err, SynthesisError::AssignmentMissing);
+
+        let err = err.with_variable(Variable::Witness(1));
+        assert_eq!(err.root(), SynthesisError::AssignmentMissing);
+        assert_eq!(err.variable(), Some(Variable::Witness(1)));
+
+        let err = err.with_context("inner").with_context("outer");
+        assert_eq!(err.namespaces(), ["inner", "outer"]);
+        assert_eq!(
+            format!("{}", err),
+            "synthesis failed while assigning Witness(1) in namespace `inner` in namespace \
+             `outer`"
+        );
+        // The error is reported once, as the source of its context.
+        let source = ark_std::error::Error::source(&err).unwrap();
+        assert_eq!(
+            format!("{}", source),
+            "an assignment for a variable could not be computed"
+        );
+        assert_eq!(SynthesisError::from(err), SynthesisError::AssignmentMissing);
+        Ok(())
+    }
+
     /// Example meant to follow as closely as possible the excellent R1CS
     /// write-up by [Vitalik Buterin](https://vitalik.eth.limo/general/2016/12/10/qap.html)
     /// and demonstrate how to construct such matrices in arkworks.
@@ -1,3 +1,7 @@
+#[cfg(feature = "std")]
+use crate::r1cs::ConstraintTrace;
+use crate::r1cs::Variable;
+use ark_std::vec::Vec;
 use core::fmt;
 
 /// This is an error that could occur during circuit synthesis contexts,
@@ -21,6 +25,34 @@ pub enum SynthesisError {
     MalformedVerifyingKey,
     /// During CRS generation, we observed an unconstrained auxiliary variable
     UnconstrainedVariable,
+    /// During synthesis, a namespace enforced more constraints than its
+    /// budget allows.
+    BudgetExceeded {
+        /// The name of the namespace whose budget was exceeded.
+        ns: &'static str,
+        /// The number of constraints the namespace attempted to use.
+        used: usize,
+        /// The budget of the namespace.
+        budget: usize,
+    },
+}
+
+impl SynthesisError {
+    /// Attaches the name of the namespace `ns` to `self`.
+    ///
+    /// With the `std` feature, this also captures the current
+    /// `ConstraintTrace`.
+    pub fn with_context(self, ns: &'static str) -> ContextualError {
+        ContextualError::from(self).with_context(ns)
+    }
+
+    /// Attaches the variable whose assignment caused `self` to `self`.
+    ///
+    /// With the `std` feature, this also captures the current
+    /// `ConstraintTrace`.
+    pub fn with_variable(self, variable: Variable) -> ContextualError {
+        ContextualError::from(self).with_variable(variable)
+    }
 }
 
 impl ark_std::error::Error for SynthesisError {}
@@ -42,6 +74,113 @@ impl fmt::Display for SynthesisError {
             SynthesisError::UnconstrainedVariable => {
                 write!(f, "auxiliary variable was unconstrained")
             },
+            SynthesisError::BudgetExceeded { ns, used, budget } => write!(
+                f,
+                "namespace `{}` used {} constraints, exceeding its budget of {}",
+                ns, used, budget
+            ),
+        }
+    }
+}
+
+/// A `SynthesisError`, together with the context in which it occurred.
+///
+/// The error itself is reported as the `source` of a `ContextualError`, and
+/// its `Display` implementation only describes the context.
+#[derive(Clone, Debug)]
+pub struct ContextualError {
+    error: SynthesisError,
+    namespaces: Vec<&'static str>,
+    variable: Option<Variable>,
+    #[cfg(feature = "std")]
+    trace: Option<ConstraintTrace>,
+}
+
+impl ContextualError {
+    /// Attaches the name of an enclosing namespace `ns` to `self`.
+    pub fn with_context(mut self, ns: &'static str) -> Self {
+        self.namespaces.push(ns);
+        self
+    }
+
+    /// Attaches the variable whose assignment caused the error to `self`.
+    pub fn with_variable(mut self, variable: Variable) -> Self {
+        self.variable = Some(variable);
+        self
+    }
+
+    /// The underlying error, with all context removed.
+    pub fn root(&self) -> SynthesisError {
+        self.error
+    }
+
+    /// The names of the namespaces in which the error occurred, from the
+    /// innermost to the outermost.
+    pub fn namespaces(&self) -> &[&'static str] {
+        &self.namespaces
+    }
+
+    /// The variable whose assignment caused the error, if known.
+    pub fn variable(&self) -> Option<Variable> {
+        self.variable
+    }
+
+    /// The constraint trace at the point where context was first attached, if
+    /// a `ConstraintLayer` was active.
+    #[cfg(feature = "std")]
+    pub fn trace(&self) -> Option<&ConstraintTrace> {
+        self.trace.as_ref()
+    }
+}
+
+impl From<SynthesisError> for ContextualError {
+    fn from(error: SynthesisError) -> Self {
+        Self {
+            error,
+            namespaces: Vec::new(),
+            variable: None,
+            #[cfg(feature = "std")]
+            trace: ConstraintTrace::capture(),
+        }
+    }
+}
+
+impl From<ContextualError> for SynthesisError {
+    fn from(error: ContextualError) -> Self {
+        error.error
+    }
+}
+
+// The trace is diagnostic only, and does not take part in comparisons.
+impl PartialEq for ContextualError {
+    fn eq(&self, other: &Self) -> bool {
+        self.error == other.error
+            && self.namespaces == other.namespaces
+            && self.variable == other.variable
+    }
+}
+
+impl Eq for ContextualError {}
+
+impl ark_std::error::Error for ContextualError {
+    fn source(&self) -> Option<&(dyn ark_std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl fmt::Display for ContextualError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "synthesis failed")?;
+        if let Some(variable) = self.variable {
+            write!(f, " while assigning {:?}", variable)?;
+        }
+        for ns in &self.namespaces {
+            write!(f, " in namespace `{}`", ns)?;
+        }
+        #[cfg(feature = "std")]
+        if let Some(trace) = &self.trace {
+            write!(f, "\n{}", trace)?;
         }
+        Ok(())
     }
 }
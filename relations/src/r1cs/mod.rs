@@ -9,6 +9,7 @@ pub type Result<T> = core::result::Result<T, SynthesisError>;
 mod impl_lc;
 mod constraint_system;
 mod error;
+mod step_circuit;
 #[cfg(feature = "std")]
 mod trace;
 
@@ -19,10 +20,11 @@ pub use tracing::info_span;
 
 pub use ark_ff::{Field, ToConstraintField};
 pub use constraint_system::{
-    ConstraintMatrices, ConstraintSynthesizer, ConstraintSystem, ConstraintSystemRef, Namespace,
-    OptimizationGoal, SynthesisMode,
+    ConstraintMatrices, ConstraintSynthesizer, ConstraintSystem, ConstraintSystemRef,
+    ConstraintSystemStatistics, CsrMatrix, Namespace, OptimizationGoal, SynthesisMode,
 };
-pub use error::SynthesisError;
+pub use error::{ContextualError, SynthesisError};
+pub use step_circuit::{new_input_state, new_witness_state, StepCircuit, StepSynthesizer};
 
 use core::cmp::Ordering;
 
@@ -62,7 +64,7 @@ macro_rules! ns {
         let _enter_guard = span.enter();
         core::mem::forget(_enter_guard);
         core::mem::forget(span);
-        $crate::r1cs::Namespace::new($cs.clone(), id)
+        $crate::r1cs::Namespace::new($cs.clone(), id).named($name)
     }};
 }
 
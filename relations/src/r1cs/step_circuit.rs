@@ -0,0 +1,177 @@
+//! Step circuits for incrementally verifiable computation (IVC), as used by
+//! folding schemes such as Nova.
+//!
+//! The state is passed as raw `Variable`s, since ark-relations does not depend
+//! on the gadgets of ark-r1cs-std. Frameworks whose step interface uses
+//! `FpVar`s can convert between the two.
+
+use crate::r1cs::{
+    ConstraintSynthesizer, ConstraintSystemRef, LinearCombination, SynthesisError, Variable,
+};
+use ark_ff::Field;
+use ark_std::vec::Vec;
+
+/// A single step `z_out = F(z_in)` of an incrementally verifiable computation,
+/// where the state `z_in` and `z_out` consists of `self.arity()` field
+/// elements.
+///
+/// Folding frameworks synthesize the step circuit once per step, together with
+/// their own verifier circuit, and thread `z_out` of each step into `z_in` of
+/// the next.
+pub trait StepCircuit<F: Field> {
+    /// The number of field elements in the state.
+    fn arity(&self) -> usize;
+
+    /// Generates the constraints of one step inside `cs`, and returns the
+    /// variables holding the new state `z_out`. `z_in` holds the
+    /// `self.arity()` variables of the current state.
+    fn generate_step_constraints(
+        &self,
+        cs: ConstraintSystemRef<F>,
+        z_in: &[Variable],
+    ) -> crate::r1cs::Result<Vec<Variable>>;
+}
+
+/// Allocates the `arity` public instance variables of a state, whose values
+/// are given by `state`. `state` is only used outside of setup mode, and
+/// `None` then results in `SynthesisError::AssignmentMissing`.
+///
+/// # Panics
+/// This method panics if `state` does not have `arity` elements.
+pub fn new_input_state<F: Field>(
+    cs: &ConstraintSystemRef<F>,
+    arity: usize,
+    state: Option<&[F]>,
+) -> crate::r1cs::Result<Vec<Variable>> {
+    new_state(cs, arity, state, true)
+}
+
+/// Allocates the `arity` private witness variables of a state, whose values
+/// are given by `state`. `state` is only used outside of setup mode, and
+/// `None` then results in `SynthesisError::AssignmentMissing`.
+///
+/// # Panics
+/// This method panics if `state` does not have `arity` elements.
+pub fn new_witness_state<F: Field>(
+    cs: &ConstraintSystemRef<F>,
+    arity: usize,
+    state: Option<&[F]>,
+) -> crate::r1cs::Result<Vec<Variable>> {
+    new_state(cs, arity, state, false)
+}
+
+fn new_state<F: Field>(
+    cs: &ConstraintSystemRef<F>,
+    arity: usize,
+    state: Option<&[F]>,
+    is_input: bool,
+) -> crate::r1cs::Result<Vec<Variable>> {
+    if let Some(state) = state {
+        assert_eq!(state.len(), arity);
+    }
+    (0..arity)
+        .map(|i| {
+            let value = || {
+                state
+                    .map(|state| state[i])
+                    .ok_or(SynthesisError::AssignmentMissing)
+            };
+            if is_input {
+                cs.new_input_variable(value)
+            } else {
+                cs.new_witness_variable(value)
+            }
+        })
+        .collect()
+}
+
+/// Synthesizes a single step of a `StepCircuit` as a standalone circuit,
+/// whose public input is `z_in` followed by `z_out`.
+pub struct StepSynthesizer<F: Field, C: StepCircuit<F>> {
+    /// The step circuit.
+    pub circuit: C,
+    /// The input state of the step. This may be `None` in setup mode.
+    pub z_in: Option<Vec<F>>,
+}
+
+impl<F: Field, C: StepCircuit<F>> ConstraintSynthesizer<F> for StepSynthesizer<F, C> {
+    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> crate::r1cs::Result<()> {
+        let arity = self.circuit.arity();
+        let z_in = new_input_state(&cs, arity, self.z_in.as_deref())?;
+        let z_out = self.circuit.generate_step_constraints(cs.clone(), &z_in)?;
+        assert_eq!(z_out.len(), arity);
+
+        for var in z_out {
+            let public = cs.new_input_variable(|| {
+                cs.assigned_value(var)
+                    .ok_or(SynthesisError::AssignmentMissing)
+            })?;
+            cs.enforce_linear_constraint(LinearCombination::from(var) - public)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::r1cs::*;
+    use ark_test_curves::bls12_381::Fr;
+
+    /// `(x, y) -> (y, x + y²)`.
+    struct SquareFibonacci;
+
+    impl StepCircuit<Fr> for SquareFibonacci {
+        fn arity(&self) -> usize {
+            2
+        }
+
+        fn generate_step_constraints(
+            &self,
+            cs: ConstraintSystemRef<Fr>,
+            z_in: &[Variable],
+        ) -> crate::r1cs::Result<Vec<Variable>> {
+            let (x, y) = (z_in[0], z_in[1]);
+            let y_sq = cs.new_witness_variable(|| {
+                let y = cs
+                    .assigned_value(y)
+                    .ok_or(SynthesisError::AssignmentMissing)?;
+                Ok(y * y)
+            })?;
+            cs.enforce_constraint(lc!() + y, lc!() + y, lc!() + y_sq)?;
+            let next = cs.new_lc(lc!() + x + y_sq)?;
+            Ok(vec![y, next])
+        }
+    }
+
+    #[test]
+    fn step_synthesizer() -> crate::r1cs::Result<()> {
+        let mut z = vec![Fr::from(1u8), Fr::from(2u8)];
+        for expected in [5u16, 27, 734] {
+            let cs = ConstraintSystem::<Fr>::new_ref();
+            let step = StepSynthesizer {
+                circuit: SquareFibonacci,
+                z_in: Some(z.clone()),
+            };
+            step.generate_constraints(cs.clone())?;
+            cs.finalize();
+            assert!(cs.is_satisfied()?);
+
+            let cs = cs.borrow().unwrap();
+            assert_eq!(cs.instance_assignment[1..3], z[..]);
+            z = cs.instance_assignment[3..].to_vec();
+            assert_eq!(z[1], Fr::from(expected as u64));
+        }
+
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        cs.set_mode(SynthesisMode::Setup);
+        let step = StepSynthesizer {
+            circuit: SquareFibonacci,
+            z_in: None,
+        };
+        step.generate_constraints(cs.clone())?;
+        assert_eq!(cs.num_instance_variables(), 5);
+        assert_eq!(cs.num_constraints(), 3);
+        Ok(())
+    }
+}
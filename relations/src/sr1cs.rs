@@ -0,0 +1,217 @@
+//! Squared Rank-One Constraint Systems (SR1CS), and conversion from R1CS.
+//!
+//! An SR1CS enforces constraints of the form `⟨u_i, z⟩² = ⟨w_i, z⟩`. Every
+//! R1CS constraint `⟨a, z⟩ ⋅ ⟨b, z⟩ = ⟨c, z⟩` can be rewritten, over fields of
+//! characteristic other than two, as the pair of square constraints
+//! `⟨a - b, z⟩² = t` and `⟨a + b, z⟩² = 4 ⋅ ⟨c, z⟩ + t`, where `t` is a new
+//! witness variable. Constraints with `a = b` are already square, and are
+//! kept as a single constraint.
+
+use crate::r1cs::{ConstraintMatrices, Matrix};
+use ark_ff::Field;
+use ark_std::{collections::BTreeMap, vec, vec::Vec};
+
+/// The U and W matrices of a Squared Rank-One Constraint System.
+/// Also contains metadata on the structure of the constraint system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sr1csMatrices<F: Field> {
+    /// The number of variables that are "public instances" to the constraint
+    /// system.
+    pub num_instance_variables: usize,
+    /// The number of variables that are "private witnesses" to the constraint
+    /// system.
+    pub num_witness_variables: usize,
+    /// The number of constraints in the constraint system.
+    pub num_constraints: usize,
+
+    /// The U constraint matrix.
+    pub u: Matrix<F>,
+    /// The W constraint matrix.
+    pub w: Matrix<F>,
+}
+
+/// Maps an assignment of an R1CS to an assignment of the SR1CS produced by
+/// `from_r1cs`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WitnessMap<F: Field> {
+    /// The number of instance variables of the R1CS, including the constant
+    /// `one`.
+    num_instance_variables: usize,
+    /// The number of witness variables of the original R1CS.
+    num_witness_variables: usize,
+    /// For each new witness variable `t`, the row `a - b` such that
+    /// `t = ⟨a - b, z⟩²`.
+    new_witnesses: Matrix<F>,
+}
+
+impl<F: Field> WitnessMap<F> {
+    /// Extends the witness assignment of the original R1CS to a witness
+    /// assignment of the SR1CS. `instance_assignment` must include the
+    /// constant `one` at index 0, as in `ConstraintSystem::instance_assignment`.
+    ///
+    /// # Panics
+    /// This method panics if `instance_assignment` or `witness_assignment`
+    /// does not have the length of the original instance or witness.
+    pub fn apply(&self, instance_assignment: &[F], witness_assignment: &[F]) -> Vec<F> {
+        assert_eq!(instance_assignment.len(), self.num_instance_variables);
+        assert_eq!(witness_assignment.len(), self.num_witness_variables);
+        let mut witness = witness_assignment.to_vec();
+        witness.extend(
+            self.new_witnesses
+                .iter()
+                .map(|row| evaluate_row(row, instance_assignment, witness_assignment).square()),
+        );
+        witness
+    }
+}
+
+impl<F: Field> Sr1csMatrices<F> {
+    /// Checks whether the assignment given by `instance_assignment` (including
+    /// the constant `one` at index 0) and `witness_assignment` satisfies all
+    /// constraints.
+    pub fn is_satisfied(&self, instance_assignment: &[F], witness_assignment: &[F]) -> bool {
+        instance_assignment.len() == self.num_instance_variables
+            && witness_assignment.len() == self.num_witness_variables
+            && self.u.iter().zip(&self.w).all(|(u, w)| {
+                evaluate_row(u, instance_assignment, witness_assignment).square()
+                    == evaluate_row(w, instance_assignment, witness_assignment)
+            })
+    }
+}
+
+/// Converts the R1CS given by `matrices` into an SR1CS, returning the SR1CS
+/// and the map that extends R1CS witnesses to SR1CS witnesses.
+///
+/// The instance variables are unchanged, and new witness variables are
+/// appended after the original ones. This conversion is only meaningful over
+/// fields whose characteristic is not two.
+pub fn from_r1cs<F: Field>(matrices: &ConstraintMatrices<F>) -> (Sr1csMatrices<F>, WitnessMap<F>) {
+    let four = F::from(4u8);
+    let mut u = Vec::with_capacity(2 * matrices.num_constraints);
+    let mut w = Vec::with_capacity(2 * matrices.num_constraints);
+    let mut new_witnesses = Vec::new();
+
+    for ((a, b), c) in matrices.a.iter().zip(&matrices.b).zip(&matrices.c) {
+        let a_minus_b = add_rows(a, b, -F::one());
+        if a_minus_b.is_empty() {
+            // `a = b`, so the constraint is already square.
+            u.push(a.clone());
+            w.push(c.clone());
+            continue;
+        }
+
+        // `t = ⟨a - b, z⟩²`.
+        let t =
+            matrices.num_instance_variables + matrices.num_witness_variables + new_witnesses.len();
+        u.push(a_minus_b.clone());
+        w.push(vec![(F::one(), t)]);
+
+        // `⟨a + b, z⟩² = 4 ⋅ ⟨c, z⟩ + t`.
+        u.push(add_rows(a, b, F::one()));
+        w.push(add_rows(&[(F::one(), t)], c, four));
+
+        new_witnesses.push(a_minus_b);
+    }
+
+    let sr1cs = Sr1csMatrices {
+        num_instance_variables: matrices.num_instance_variables,
+        num_witness_variables: matrices.num_witness_variables + new_witnesses.len(),
+        num_constraints: u.len(),
+        u,
+        w,
+    };
+    let witness_map = WitnessMap {
+        num_instance_variables: matrices.num_instance_variables,
+        num_witness_variables: matrices.num_witness_variables,
+        new_witnesses,
+    };
+    (sr1cs, witness_map)
+}
+
+/// Computes `a + scalar ⋅ b`, omitting zero entries.
+fn add_rows<F: Field>(a: &[(F, usize)], b: &[(F, usize)], scalar: F) -> Vec<(F, usize)> {
+    let mut sum = BTreeMap::new();
+    for &(coeff, i) in a {
+        *sum.entry(i).or_insert_with(F::zero) += coeff;
+    }
+    for &(coeff, i) in b {
+        *sum.entry(i).or_insert_with(F::zero) += coeff * scalar;
+    }
+    sum.into_iter()
+        .filter(|(_, coeff)| !coeff.is_zero())
+        .map(|(i, coeff)| (coeff, i))
+        .collect()
+}
+
+/// Computes `⟨row, z⟩`, where `z = (instance_assignment, witness_assignment)`.
+fn evaluate_row<F: Field>(row: &[(F, usize)], instance: &[F], witness: &[F]) -> F {
+    row.iter()
+        .map(|&(coeff, i)| {
+            let value = if i < instance.len() {
+                instance[i]
+            } else {
+                witness[i - instance.len()]
+            };
+            coeff * value
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lc, r1cs::*};
+    use ark_ff::One;
+    use ark_test_curves::bls12_381::Fr;
+
+    #[test]
+    fn from_r1cs_round_trip() -> crate::r1cs::Result<()> {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let three = Fr::from(3u8);
+        let out = cs.new_input_variable(|| Ok(Fr::from(35u8)))?;
+        let x = cs.new_witness_variable(|| Ok(three))?;
+        let x_sq = cs.new_witness_variable(|| Ok(three * three))?;
+        let x_cu = cs.new_witness_variable(|| Ok(three * three * three))?;
+        cs.enforce_constraint(lc!() + x, lc!() + x, lc!() + x_sq)?;
+        cs.enforce_constraint(lc!() + x_sq, lc!() + x, lc!() + x_cu)?;
+        cs.enforce_constraint(
+            lc!() + x_cu + x + (Fr::from(5u8), Variable::One),
+            lc!() + Variable::One,
+            lc!() + out,
+        )?;
+        cs.finalize();
+        assert!(cs.is_satisfied()?);
+
+        let matrices = cs.to_matrices().unwrap();
+        let (sr1cs, witness_map) = from_r1cs(&matrices);
+        // The first constraint is already square.
+        assert_eq!(sr1cs.num_constraints, 5);
+        assert_eq!(sr1cs.num_witness_variables, 5);
+
+        let cs = cs.borrow().unwrap();
+        let witness = witness_map.apply(&cs.instance_assignment, &cs.witness_assignment);
+        assert!(sr1cs.is_satisfied(&cs.instance_assignment, &witness));
+
+        let mut bad_witness = cs.witness_assignment.clone();
+        bad_witness[2] += Fr::one();
+        let bad_witness = witness_map.apply(&cs.instance_assignment, &bad_witness);
+        assert!(!sr1cs.is_satisfied(&cs.instance_assignment, &bad_witness));
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn witness_map_rejects_short_instance() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let out = cs.new_input_variable(|| Ok(Fr::from(6u8))).unwrap();
+        let x = cs.new_witness_variable(|| Ok(Fr::from(2u8))).unwrap();
+        let y = cs.new_witness_variable(|| Ok(Fr::from(3u8))).unwrap();
+        cs.enforce_constraint(lc!() + x, lc!() + y, lc!() + out)
+            .unwrap();
+        cs.finalize();
+        let (_, witness_map) = from_r1cs(&cs.to_matrices().unwrap());
+
+        let cs = cs.borrow().unwrap();
+        witness_map.apply(&cs.instance_assignment[..1], &cs.witness_assignment);
+    }
+}
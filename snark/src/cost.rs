@@ -0,0 +1,128 @@
+//! Estimation of the cost of SNARK verification, e.g., for on-chain
+//! deployment.
+
+use crate::SNARK;
+use ark_ff::PrimeField;
+
+/// The group and hash operations performed by a verifier.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VerifierOperations {
+    /// The number of pairings, all of which are checked together as a single
+    /// product of pairings.
+    pub num_pairings: u64,
+    /// The number of additions in G1.
+    pub num_g1_additions: u64,
+    /// The number of scalar multiplications in G1.
+    pub num_g1_scalar_muls: u64,
+    /// The number of additions in G2.
+    pub num_g2_additions: u64,
+    /// The number of scalar multiplications in G2.
+    pub num_g2_scalar_muls: u64,
+    /// The number of hash function invocations.
+    pub num_hashes: u64,
+}
+
+/// The cost of each verifier operation. A cost of `None` means that the
+/// operation is not supported in the target environment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CostTable {
+    /// The fixed cost of a product-of-pairings check.
+    pub pairing_base: Option<u64>,
+    /// The cost of each pairing in a product-of-pairings check.
+    pub pairing: Option<u64>,
+    /// The cost of an addition in G1.
+    pub g1_addition: Option<u64>,
+    /// The cost of a scalar multiplication in G1.
+    pub g1_scalar_mul: Option<u64>,
+    /// The cost of an addition in G2.
+    pub g2_addition: Option<u64>,
+    /// The cost of a scalar multiplication in G2.
+    pub g2_scalar_mul: Option<u64>,
+    /// The cost of a hash function invocation.
+    pub hash: Option<u64>,
+}
+
+impl CostTable {
+    /// Gas prices of the BN254 precompiles on the EVM, as per
+    /// [EIP-1108](https://eips.ethereum.org/EIPS/eip-1108). The EVM has no
+    /// precompiles for G2 arithmetic, and hashes are priced as one Keccak-256
+    /// invocation over a single 32-byte word.
+    pub const EVM_BN254: Self = Self {
+        pairing_base: Some(45_000),
+        pairing: Some(34_000),
+        g1_addition: Some(150),
+        g1_scalar_mul: Some(6_000),
+        g2_addition: None,
+        g2_scalar_mul: None,
+        hash: Some(36),
+    };
+
+    /// The total cost of `ops`, or `None` if `ops` contains an operation that
+    /// is not supported by `self`.
+    pub fn cost(&self, ops: &VerifierOperations) -> Option<u64> {
+        fn term(count: u64, cost: Option<u64>) -> Option<u64> {
+            if count == 0 {
+                Some(0)
+            } else {
+                cost?.checked_mul(count)
+            }
+        }
+
+        let pairing_base = if ops.num_pairings == 0 {
+            0
+        } else {
+            self.pairing_base?
+        };
+        [
+            term(ops.num_pairings, self.pairing),
+            term(ops.num_g1_additions, self.g1_addition),
+            term(ops.num_g1_scalar_muls, self.g1_scalar_mul),
+            term(ops.num_g2_additions, self.g2_addition),
+            term(ops.num_g2_scalar_muls, self.g2_scalar_mul),
+            term(ops.num_hashes, self.hash),
+        ]
+        .iter()
+        .try_fold(pairing_base, |acc, term| acc.checked_add((*term)?))
+    }
+}
+
+/// A SNARK whose verification cost can be estimated from its verifying key.
+pub trait VerifierCost<F: PrimeField>: SNARK<F> {
+    /// The operations performed by `Self::verify` for the verifying key
+    /// `circuit_vk`.
+    fn verifier_operations(circuit_vk: &Self::VerifyingKey) -> VerifierOperations;
+
+    /// The total cost of `Self::verify` for the verifying key `circuit_vk`
+    /// according to `table`, or `None` if verification requires an operation
+    /// that is not supported by `table`.
+    fn verifier_cost(circuit_vk: &Self::VerifyingKey, table: &CostTable) -> Option<u64> {
+        table.cost(&Self::verifier_operations(circuit_vk))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evm_cost() {
+        // A Groth16 verifier with two public inputs.
+        let ops = VerifierOperations {
+            num_pairings: 4,
+            num_g1_additions: 2,
+            num_g1_scalar_muls: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            CostTable::EVM_BN254.cost(&ops),
+            Some(45_000 + 4 * 34_000 + 2 * 150 + 2 * 6_000)
+        );
+        assert_eq!(CostTable::EVM_BN254.cost(&Default::default()), Some(0));
+
+        let ops = VerifierOperations {
+            num_g2_additions: 1,
+            ..ops
+        };
+        assert_eq!(CostTable::EVM_BN254.cost(&ops), None);
+    }
+}
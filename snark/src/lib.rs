@@ -10,11 +10,15 @@
 )]
 #![forbid(unsafe_code)]
 
+pub mod cost;
+pub mod transcript;
+
 use ark_ff::PrimeField;
 use ark_relations::r1cs::ConstraintSynthesizer;
 use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 use ark_std::fmt::Debug;
 use ark_std::rand::{CryptoRng, RngCore};
+use ark_std::vec::Vec;
 
 /// The basic functionality for a SNARK.
 pub trait SNARK<F: PrimeField> {
@@ -90,6 +94,81 @@ pub trait CircuitSpecificSetupSNARK<F: PrimeField>: SNARK<F> {
     }
 }
 
+/// A single row of the `A`, `B`, and `C` constraint matrices, in the same
+/// sparse `(coefficient, variable index)` form as
+/// [`ConstraintMatrices`](ark_relations::r1cs::ConstraintMatrices).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MatrixRow<F: PrimeField> {
+    /// The row of the `A` matrix.
+    pub a: Vec<(F, usize)>,
+    /// The row of the `B` matrix.
+    pub b: Vec<(F, usize)>,
+    /// The row of the `C` matrix.
+    pub c: Vec<(F, usize)>,
+}
+
+/// A SNARK whose prover can consume the witness assignment and the constraint
+/// matrices as streams, instead of requiring them to be fully materialized in
+/// memory. Implementations are expected to process the streams in fixed-size
+/// chunks, so that proving memory does not grow with the size of the circuit.
+pub trait StreamingSNARK<F: PrimeField>: SNARK<F> {
+    /// Generates a proof of satisfaction of the constraint system whose rows
+    /// are yielded, in order, by `matrix_rows`, with respect to the public
+    /// input `public_input` and the witness assignment yielded, in order, by
+    /// `witness`.
+    ///
+    /// The prover may need more than one pass over each stream, so cloning
+    /// `witness` and `matrix_rows` should be cheap (e.g., re-opening a file).
+    fn prove_streaming<W, M, R>(
+        circuit_pk: &Self::ProvingKey,
+        public_input: &[F],
+        witness: W,
+        matrix_rows: M,
+        rng: &mut R,
+    ) -> Result<Self::Proof, Self::Error>
+    where
+        W: IntoIterator<Item = F> + Clone,
+        M: IntoIterator<Item = MatrixRow<F>> + Clone,
+        R: RngCore + CryptoRng;
+}
+
+/// A SNARK whose proofs can be accumulated, as in recursive proof systems
+/// such as Darlin. Instead of fully verifying each proof, the verifier folds
+/// the expensive part of verification into an accumulator, and only checks
+/// the final accumulator.
+pub trait AccumulationScheme<F: PrimeField>: SNARK<F> {
+    /// The accumulator, which represents the deferred part of the
+    /// verification of every proof accumulated into it.
+    type Accumulator: Clone + CanonicalSerialize + CanonicalDeserialize;
+
+    /// The proof that an accumulator was correctly computed.
+    type AccumulationProof: Clone + CanonicalSerialize + CanonicalDeserialize;
+
+    /// Accumulates the proofs in `proofs`, each given with its verifying key
+    /// and public input, together with the accumulators in `accumulators`,
+    /// into a new accumulator.
+    fn accumulate<R: RngCore + CryptoRng>(
+        proofs: &[(&Self::VerifyingKey, &[F], &Self::Proof)],
+        accumulators: &[Self::Accumulator],
+        rng: &mut R,
+    ) -> Result<(Self::Accumulator, Self::AccumulationProof), Self::Error>;
+
+    /// Checks that `new_accumulator` was correctly obtained by accumulating
+    /// `proofs` and `accumulators`. This check is succinct, and is what a
+    /// recursive circuit verifies at every step.
+    fn verify_accumulation(
+        proofs: &[(&Self::VerifyingKey, &[F], &Self::Proof)],
+        accumulators: &[Self::Accumulator],
+        new_accumulator: &Self::Accumulator,
+        accumulation_proof: &Self::AccumulationProof,
+    ) -> Result<bool, Self::Error>;
+
+    /// Checks that `accumulator` is valid. If so, every proof accumulated
+    /// into it, directly or through an earlier accumulator whose
+    /// accumulation was verified, is valid.
+    fn decide(accumulator: &Self::Accumulator) -> Result<bool, Self::Error>;
+}
+
 /// A helper type for universal-setup SNARKs, which must infer their computation
 /// size bounds.
 pub enum UniversalSetupIndexError<Bound, E> {
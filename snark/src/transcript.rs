@@ -0,0 +1,158 @@
+//! Fiat–Shamir transcripts, which turn public-coin interactive protocols into
+//! non-interactive ones by deriving the verifier's challenges from everything
+//! the prover has sent so far.
+
+use ark_ff::PrimeField;
+use ark_serialize::CanonicalSerialize;
+use ark_std::{vec, vec::Vec};
+use digest::Digest;
+
+/// A Fiat–Shamir transcript with [Merlin](https://merlin.cool)-style domain
+/// separation: every message and every challenge is bound to a label, and the
+/// transcript itself is bound to a protocol label when it is created.
+///
+/// The prover and the verifier must append the same messages, with the same
+/// labels and in the same order, in order to derive the same challenges.
+pub trait Transcript {
+    /// Creates a new transcript for the protocol identified by `label`.
+    fn new(label: &'static [u8]) -> Self;
+
+    /// Appends the bytes of `message`, labelled with `label`, to the
+    /// transcript.
+    fn append_message(&mut self, label: &'static [u8], message: &[u8]);
+
+    /// Fills `dest` with challenge bytes labelled with `label`. The challenge
+    /// depends on everything appended so far, and is itself appended to the
+    /// transcript, so consecutive challenges are independent.
+    fn challenge_bytes(&mut self, label: &'static [u8], dest: &mut [u8]);
+
+    /// Appends the compressed serialization of `value` (e.g., a field element
+    /// or a curve point), labelled with `label`, to the transcript.
+    fn append_serializable<T: CanonicalSerialize + ?Sized>(
+        &mut self,
+        label: &'static [u8],
+        value: &T,
+    ) {
+        let mut bytes = Vec::with_capacity(value.compressed_size());
+        value
+            .serialize_compressed(&mut bytes)
+            .expect("serializing to a vector cannot fail");
+        self.append_message(label, &bytes);
+    }
+
+    /// Derives a field element challenge labelled with `label`.
+    ///
+    /// The challenge is obtained by reducing 128 bits more than the size of
+    /// the modulus, so that it is statistically close to uniform.
+    fn challenge_field<F: PrimeField>(&mut self, label: &'static [u8]) -> F {
+        let mut bytes = vec![0u8; (F::MODULUS_BIT_SIZE as usize).div_ceil(8) + 16];
+        self.challenge_bytes(label, &mut bytes);
+        F::from_le_bytes_mod_order(&bytes)
+    }
+}
+
+/// A [`Transcript`] built from a hash function `D`.
+///
+/// Every appended message updates a running hash state. Challenges are derived
+/// by hashing a copy of that state together with the challenge label and a
+/// block counter, and are then appended to the running state. Messages and
+/// challenges are absorbed under distinct tags, so a message can never be
+/// mistaken for a challenge with the same label and bytes.
+#[derive(Clone)]
+pub struct DigestTranscript<D: Digest + Clone> {
+    state: D,
+}
+
+impl<D: Digest + Clone> DigestTranscript<D> {
+    /// Absorbs `message`, labelled with `label`, under the tag `kind`. Every
+    /// part is length-prefixed, so distinct inputs are framed distinctly.
+    fn absorb(&mut self, kind: &[u8], label: &[u8], message: &[u8]) {
+        for part in [kind, label, message] {
+            self.state.update((part.len() as u64).to_le_bytes());
+            self.state.update(part);
+        }
+    }
+}
+
+impl<D: Digest + Clone> Transcript for DigestTranscript<D> {
+    fn new(label: &'static [u8]) -> Self {
+        let mut transcript = Self { state: D::new() };
+        transcript.absorb(b"dom-sep", label, &[]);
+        transcript
+    }
+
+    fn append_message(&mut self, label: &'static [u8], message: &[u8]) {
+        self.absorb(b"message", label, message);
+    }
+
+    fn challenge_bytes(&mut self, label: &'static [u8], dest: &mut [u8]) {
+        let mut filled = 0;
+        let mut counter = 0u64;
+        while filled < dest.len() {
+            let mut block = self.state.clone();
+            block.update(b"challenge");
+            block.update((label.len() as u64).to_le_bytes());
+            block.update(label);
+            block.update((dest.len() as u64).to_le_bytes());
+            block.update(counter.to_le_bytes());
+            let block = block.finalize();
+
+            let len = block.len().min(dest.len() - filled);
+            dest[filled..filled + len].copy_from_slice(&block[..len]);
+            filled += len;
+            counter += 1;
+        }
+        self.absorb(b"challenge", label, dest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ark_test_curves::bls12_381::Fr;
+    use blake2::Blake2s256;
+
+    type TestTranscript = DigestTranscript<Blake2s256>;
+
+    #[test]
+    fn challenges_depend_on_messages_and_labels() {
+        let challenge = |protocol, label, message: &[u8]| {
+            let mut transcript = TestTranscript::new(protocol);
+            transcript.append_message(label, message);
+            transcript.challenge_field::<Fr>(b"challenge")
+        };
+
+        let expected = challenge(b"protocol", b"message", b"hello");
+        assert_eq!(challenge(b"protocol", b"message", b"hello"), expected);
+        assert_ne!(challenge(b"protocol", b"message", b"hellp"), expected);
+        assert_ne!(challenge(b"protocol", b"other", b"hello"), expected);
+        assert_ne!(challenge(b"other", b"message", b"hello"), expected);
+    }
+
+    #[test]
+    fn challenges_are_not_messages() {
+        let mut with_challenge = TestTranscript::new(b"protocol");
+        let mut bytes = [0u8; 32];
+        with_challenge.challenge_bytes(b"label", &mut bytes);
+
+        let mut with_message = TestTranscript::new(b"protocol");
+        with_message.append_message(b"label", &bytes);
+
+        assert_ne!(
+            with_challenge.challenge_field::<Fr>(b"next"),
+            with_message.challenge_field::<Fr>(b"next")
+        );
+    }
+
+    #[test]
+    fn consecutive_challenges_differ() {
+        let mut transcript = TestTranscript::new(b"protocol");
+        transcript.append_serializable(b"element", &Fr::from(7u8));
+        let mut first = [0u8; 80];
+        let mut second = [0u8; 80];
+        transcript.challenge_bytes(b"challenge", &mut first);
+        transcript.challenge_bytes(b"challenge", &mut second);
+        assert_ne!(first, second);
+        assert_ne!(first[..32], first[32..64]);
+    }
+}